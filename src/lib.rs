@@ -0,0 +1,259 @@
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+pub mod prune;
+mod session;
+
+pub use session::{ArchivingSession, ArchivingSessionBuilder};
+
+/// Results for a single URL, keyed by the provider that produced them.
+pub type ProviderResults = std::collections::BTreeMap<String, ArchivingResult>;
+/// All results, keyed by URL.
+pub type AllResults = std::collections::BTreeMap<String, ProviderResults>;
+
+/// The outcome of attempting to archive a single URL.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivingResult {
+    /// The time at which this URL was last archived (or attempted).
+    pub last_archived: NaiveDateTime,
+    /// The URL of the resulting snapshot, if archiving succeeded.
+    pub url: Option<String>,
+    /// Whether `url` points at a pre-existing snapshot rather than one
+    /// freshly created by this run.
+    pub existing_snapshot: bool,
+    /// The [`Archiver::provider`] that produced this result, e.g.
+    /// `"wayback"` or `"archive-today"`.
+    pub provider: String,
+}
+
+/// Errors that can occur while archiving a URL.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// The backend is rate-limiting us; callers should back off and
+    /// retry.
+    BandwidthExceeded,
+    /// The request did not complete within the configured timeout.
+    Timeout,
+    /// The underlying HTTP request failed.
+    RequestFailed(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::BandwidthExceeded => write!(f, "bandwidth exceeded"),
+            ArchiveError::Timeout => write!(f, "timed out"),
+            ArchiveError::RequestFailed(msg) => write!(f, "request failed: {}", msg),
+        }
+    }
+}
+
+/// A backend capable of archiving a URL and handing back the resulting
+/// snapshot. Implement this to plug in a new archival service.
+#[async_trait]
+pub trait Archiver: Send + Sync {
+    /// A short, lowercase identifier for this backend. Stored on every
+    /// [`ArchivingResult`] it produces so results from multiple backends
+    /// can be told apart in a merged output file.
+    fn provider(&self) -> &'static str;
+
+    /// Archives `url`, returning the resulting snapshot.
+    async fn archive(&self, url: &str) -> Result<ArchivingResult, ArchiveError>;
+}
+
+fn map_request_error(err: reqwest::Error) -> ArchiveError {
+    if err.is_timeout() {
+        ArchiveError::Timeout
+    } else {
+        ArchiveError::RequestFailed(err.to_string())
+    }
+}
+
+/// Archives URLs with the Internet Archive's Wayback Machine.
+pub struct WaybackMachine {
+    client: reqwest::Client,
+}
+
+impl WaybackMachine {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    /// Looks up the closest existing snapshot of `url` using the Wayback
+    /// Machine's availability API, without submitting a new capture. If
+    /// `timestamp` is given (as `YYYYMMDD`), the lookup is anchored to the
+    /// closest snapshot around that date instead of the most recent one.
+    pub async fn check_availability(
+        &self,
+        url: &str,
+        timestamp: Option<&str>,
+    ) -> Result<ArchivingResult, ArchiveError> {
+        let mut query = vec![("url", url)];
+        if let Some(timestamp) = timestamp {
+            query.push(("timestamp", timestamp));
+        }
+
+        let response = self
+            .client
+            .get("https://archive.org/wayback/available")
+            .query(&query)
+            .send()
+            .await
+            .map_err(map_request_error)?;
+
+        let parsed: AvailabilityResponse = response
+            .json()
+            .await
+            .map_err(|err| ArchiveError::RequestFailed(err.to_string()))?;
+
+        match parsed.archived_snapshots.closest {
+            Some(closest) if closest.available => Ok(ArchivingResult {
+                last_archived: parse_wayback_timestamp(&closest.timestamp)?,
+                url: Some(closest.url),
+                existing_snapshot: true,
+                provider: self.provider().to_string(),
+            }),
+            _ => Ok(ArchivingResult {
+                last_archived: Utc::now().naive_utc(),
+                url: None,
+                existing_snapshot: false,
+                provider: self.provider().to_string(),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl Archiver for WaybackMachine {
+    fn provider(&self) -> &'static str {
+        "wayback"
+    }
+
+    /// Submits `url` to the Wayback Machine's Save Page Now endpoint and
+    /// returns the resulting snapshot location.
+    async fn archive(&self, url: &str) -> Result<ArchivingResult, ArchiveError> {
+        let save_url = format!("https://web.archive.org/save/{}", url);
+
+        let response = self
+            .client
+            .get(&save_url)
+            .send()
+            .await
+            .map_err(map_request_error)?;
+
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(ArchiveError::BandwidthExceeded);
+        }
+
+        let snapshot_path = response
+            .headers()
+            .get("Content-Location")
+            .and_then(|value| value.to_str().ok())
+            .map(|path| path.to_string());
+
+        let snapshot_path = snapshot_path.ok_or_else(|| {
+            ArchiveError::RequestFailed("no Content-Location header in response".to_string())
+        })?;
+
+        Ok(ArchivingResult {
+            last_archived: Utc::now().naive_utc(),
+            url: Some(format!("https://web.archive.org{}", snapshot_path)),
+            existing_snapshot: false,
+            provider: self.provider().to_string(),
+        })
+    }
+}
+
+/// Archives URLs with [archive.today](https://archive.ph).
+pub struct ArchiveToday {
+    client: reqwest::Client,
+}
+
+impl ArchiveToday {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl Archiver for ArchiveToday {
+    fn provider(&self) -> &'static str {
+        "archive-today"
+    }
+
+    /// Submits `url` to archive.today's submission form and returns the
+    /// snapshot location it redirects to.
+    async fn archive(&self, url: &str) -> Result<ArchivingResult, ArchiveError> {
+        let response = self
+            .client
+            .post("https://archive.ph/submit/")
+            .form(&[("url", url)])
+            .send()
+            .await
+            .map_err(map_request_error)?;
+
+        let snapshot_url = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .map(|location| location.to_string())
+            .or_else(|| {
+                let final_url = response.url().as_str();
+                if final_url != "https://archive.ph/submit/" {
+                    Some(final_url.to_string())
+                } else {
+                    None
+                }
+            });
+
+        // archive.ph serves back the bare submit page, with no redirect or
+        // Location header, when it's rate-limiting or captcha-gating us.
+        let snapshot_url = snapshot_url.ok_or(ArchiveError::BandwidthExceeded)?;
+
+        Ok(ArchivingResult {
+            last_archived: Utc::now().naive_utc(),
+            url: Some(snapshot_url),
+            existing_snapshot: false,
+            provider: self.provider().to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AvailabilityResponse {
+    archived_snapshots: ArchivedSnapshots,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ArchivedSnapshots {
+    closest: Option<ClosestSnapshot>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClosestSnapshot {
+    available: bool,
+    url: String,
+    timestamp: String,
+}
+
+/// Parses a 14-digit Wayback Machine timestamp (`YYYYMMDDHHMMSS`).
+fn parse_wayback_timestamp(timestamp: &str) -> Result<NaiveDateTime, ArchiveError> {
+    NaiveDateTime::parse_from_str(timestamp, "%Y%m%d%H%M%S")
+        .map_err(|err| ArchiveError::RequestFailed(format!("invalid timestamp {}: {}", timestamp, err)))
+}
+
+/// Constructs the [`Archiver`] named by `name` (`"wayback"` or
+/// `"archive-today"`), sharing `client` with it.
+pub fn build_archiver(name: &str, client: reqwest::Client) -> Result<Arc<dyn Archiver>, String> {
+    match name {
+        "wayback" => Ok(Arc::new(WaybackMachine::new(client))),
+        "archive-today" => Ok(Arc::new(ArchiveToday::new(client))),
+        other => Err(format!(
+            "unknown archiver '{}' (expected 'wayback' or 'archive-today')",
+            other
+        )),
+    }
+}
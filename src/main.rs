@@ -1,19 +1,37 @@
 use chrono::{Duration, Utc};
-use clap::Parser;
-use indicatif::{ProgressBar, ProgressStyle};
+use clap::{Parser, Subcommand};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::collections::BTreeMap;
 use std::fs;
 use std::io::{self, BufRead, Write};
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 use std::sync::Arc;
 use std::thread;
+use tokio::sync::Mutex;
 
-mod lib;
-use crate::lib::{archive_url, ArchiveError, ArchivingResult};
+use wayback_archiver::prune::{self, RetentionPolicy};
+use wayback_archiver::{
+    build_archiver, AllResults, ArchiveError, Archiver, ArchivingResult, WaybackMachine,
+};
 
 #[derive(Parser)]
 #[clap(version = "1.0", author = "Ben Congdon <ben@congdon.dev>")]
-struct Opts {
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    archive: ArchiveOpts,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply a retention policy to an existing --out file, discarding
+    /// entries that don't match any --keep-* rule.
+    Prune(PruneOpts),
+}
+
+#[derive(Parser)]
+struct ArchiveOpts {
     /// If set, archived URLs are saved to the path specified by this flag.
     /// Otherwise, URLs are printed at the end of the command run.
     #[clap(short, long)]
@@ -25,22 +43,117 @@ struct Opts {
     /// A file containing urls to archive.
     #[clap(short, long)]
     urls_file: Option<String>,
+    /// The number of URLs to archive concurrently.
+    #[clap(short = 'j', long, default_value = "4")]
+    concurrency: usize,
+    /// The number of seconds to wait for an archival request to complete
+    /// before giving up on it.
+    #[clap(short, long, default_value = "30")]
+    timeout: u64,
+    /// How long a snapshot stays "fresh" before a URL is considered for
+    /// re-archiving, e.g. `180d` or `12w`.
+    #[clap(long, default_value = "180d", parse(try_from_str = parse_duration_spec))]
+    max_age: Duration,
+    /// Instead of submitting new captures, look up the closest existing
+    /// snapshot for each URL using the Wayback Machine's availability API.
+    /// This spends no archiving bandwidth.
+    #[clap(long)]
+    check_only: bool,
+    /// With --check-only, anchor the snapshot lookup to this date
+    /// (`YYYYMMDD`) instead of returning the most recent snapshot.
+    #[clap(long, requires = "check-only")]
+    timestamp: Option<String>,
+    /// The archive backend(s) to submit each URL to. May be repeated or
+    /// comma-separated to mirror a URL to multiple providers.
+    #[clap(long, value_delimiter = ',', default_value = "wayback")]
+    archiver: Vec<String>,
     /// URLs to archive using the Wayback Machine. URLs can also
     /// be provided using stdin, or with --urls_file.
     urls: Vec<String>,
 }
 
+#[derive(Parser)]
+struct PruneOpts {
+    /// The results file to prune in place.
+    #[clap(short, long)]
+    out: String,
+    /// Keep the N most recently archived entries, regardless of bucket.
+    #[clap(long)]
+    keep_last: Option<usize>,
+    /// Keep one entry for each of the last N days that have one.
+    #[clap(long)]
+    keep_daily: Option<usize>,
+    /// Keep one entry for each of the last N ISO weeks that have one.
+    #[clap(long)]
+    keep_weekly: Option<usize>,
+    /// Keep one entry for each of the last N months that have one.
+    #[clap(long)]
+    keep_monthly: Option<usize>,
+    /// Keep one entry for each of the last N years that have one.
+    #[clap(long)]
+    keep_yearly: Option<usize>,
+}
+
+/// Parses a duration spec like `180d` or `12w` (days/weeks).
+fn parse_duration_spec(spec: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{}': expected e.g. '180d' or '12w'", spec);
+    if spec.len() < 2 {
+        return Err(invalid());
+    }
+    let (amount, unit) = spec.split_at(spec.len() - 1);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    match unit {
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(invalid()),
+    }
+}
+
+/// The number of times a timed-out request is retried before the URL is
+/// recorded as a failure.
+const MAX_TIMEOUT_RETRIES: u32 = 3;
+
+type SharedResults = Arc<Mutex<AllResults>>;
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let opts = Opts::parse();
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Command::Prune(prune_opts)) => run_prune(prune_opts),
+        None => run_archive(cli.archive).await,
+    }
+}
+
+fn run_prune(opts: PruneOpts) -> Result<(), Box<dyn std::error::Error>> {
+    let policy = RetentionPolicy {
+        keep_last: opts.keep_last,
+        keep_daily: opts.keep_daily,
+        keep_weekly: opts.keep_weekly,
+        keep_monthly: opts.keep_monthly,
+        keep_yearly: opts.keep_yearly,
+    };
+    if policy.is_empty() {
+        return Err("prune requires at least one --keep-* rule".into());
+    }
+
+    let existing = fs::read_to_string(&opts.out)?;
+    let results: AllResults = serde_json::from_str(&existing)?;
 
+    let total: usize = results.values().map(|by_provider| by_provider.len()).sum();
+    let (pruned, removed) = prune::prune(&results, &policy);
+    eprintln!("Pruned {} of {} entries, keeping {}.", removed, total, total - removed);
+    write_results(&pruned, &opts.out)
+}
+
+async fn run_archive(opts: ArchiveOpts) -> Result<(), Box<dyn std::error::Error>> {
     let (tx, rx) = crossbeam_channel::unbounded::<String>();
 
-    let mut urls: BTreeMap<String, ArchivingResult> = BTreeMap::new();
+    let mut initial_urls: AllResults = BTreeMap::new();
     if opts.merge {
         let path = opts.out.as_ref().expect("--merge requires --out to be set");
         match fs::read_to_string(path) {
-            Ok(existing) => urls = serde_json::from_str(&existing)?,
+            Ok(existing) => initial_urls = serde_json::from_str(&existing)?,
             Err(error) => match error.kind() {
                 // Ignore "file not found" error.
                 io::ErrorKind::NotFound => {}
@@ -48,6 +161,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             },
         }
     }
+    let urls: SharedResults = Arc::new(Mutex::new(initial_urls));
 
     let total_lines_count = Arc::new(AtomicUsize::new(0));
     let total_lines_count_clone = total_lines_count.clone();
@@ -87,84 +201,212 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         });
     }
 
-    let mut num_archived = 0;
-    for (line_idx, line) in rx.into_iter().map(|l| l.trim().to_string()).enumerate() {
-        let pb = ProgressBar::new_spinner();
-        pb.enable_steady_tick(120);
-        pb.set_style(
-            ProgressStyle::default_spinner().template("{prefix:.bold.dim} {spinner:.blue} {msg}"),
-        );
-        pb.set_prefix(format!(
-            "[{}/{}]",
-            line_idx + 1,
-            total_lines_count_clone.load(SeqCst)
-        ));
-
-        if let Some(existing) = urls.get(&line) {
-            // If the last archival time of the URL was within ~6 months, accept it and move on.
-            if (Utc::now().naive_utc() - existing.last_archived) < Duration::days(30 * 6) {
-                pb.finish_with_message(format!("URL already archived: {}", line));
-                continue;
-            }
-        }
+    let multi_progress = Arc::new(MultiProgress::new());
+    let overall_bar = multi_progress.add(ProgressBar::new_spinner());
+    overall_bar.enable_steady_tick(std::time::Duration::from_millis(120));
+    overall_bar.set_style(
+        ProgressStyle::default_spinner()
+            .template("{prefix:.bold.dim} {spinner:.green} {msg}")
+            .expect("valid progress template"),
+    );
+    overall_bar.set_prefix("[total]");
 
-        pb.set_message(format!("Archiving {} ...", line));
-        loop {
-            let result = match archive_url(&line).await {
-                Ok(success) => {
-                    pb.finish_with_message(format!(
-                        "Done: {}",
-                        &success.url.as_ref().expect("archive url")
-                    ));
-                    if !success.existing_snapshot {
-                        let pb = ProgressBar::new_spinner();
-                        pb.enable_steady_tick(180);
-                        pb.set_message("Cooldown after archiving...");
-                        std::thread::sleep(Duration::seconds(3).to_std().expect("sleep duration"));
-                        pb.finish_and_clear();
+    let num_archived = Arc::new(AtomicUsize::new(0));
+    let num_urls_done = Arc::new(AtomicUsize::new(0));
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(opts.timeout))
+        .build()?;
+
+    let wayback = Arc::new(WaybackMachine::new(client.clone()));
+    let archivers: Vec<Arc<dyn Archiver>> = opts
+        .archiver
+        .iter()
+        .map(|name| build_archiver(name, client.clone()))
+        .collect::<Result<_, _>>()?;
+
+    let mut workers = Vec::with_capacity(opts.concurrency);
+    for worker_id in 0..opts.concurrency {
+        let rx = rx.clone();
+        let urls = urls.clone();
+        let out_path = opts.out.clone();
+        let multi_progress = multi_progress.clone();
+        let overall_bar = overall_bar.clone();
+        let total_lines_count = total_lines_count_clone.clone();
+        let num_archived = num_archived.clone();
+        let num_urls_done = num_urls_done.clone();
+        let archivers = archivers.clone();
+        let wayback = wayback.clone();
+        let check_only = opts.check_only;
+        let timestamp = opts.timestamp.clone();
+        let max_age = opts.max_age;
+
+        workers.push(tokio::spawn(async move {
+            let pb = multi_progress.add(ProgressBar::new_spinner());
+            pb.enable_steady_tick(std::time::Duration::from_millis(120));
+            pb.set_style(
+                ProgressStyle::default_spinner()
+                    .template("{prefix:.bold.dim} {spinner:.blue} {msg}")
+                    .expect("valid progress template"),
+            );
+            pb.set_prefix(format!("[worker {}]", worker_id));
+
+            while let Ok(line) = rx.recv() {
+                let line = line.trim().to_string();
+
+                // --check-only always queries the Wayback Machine's free
+                // availability API, regardless of --archiver.
+                if check_only {
+                    pb.set_message(format!("Checking {} ...", line));
+                    let result = match wayback.check_availability(&line, timestamp.as_deref()).await
+                    {
+                        Ok(result) => result,
+                        Err(err) => {
+                            pb.set_message(format!("Check failed: {} ({})", err, line));
+                            ArchivingResult {
+                                last_archived: Utc::now().naive_utc(),
+                                url: None,
+                                existing_snapshot: false,
+                                provider: wayback.provider().to_string(),
+                            }
+                        }
+                    };
+                    match &result.url {
+                        Some(url) => pb.set_message(format!("Done: {}", url)),
+                        None => pb.set_message(format!("No snapshot found: {}", line)),
                     }
-                    num_archived += 1;
-                    success
+                    {
+                        let mut results = urls.lock().await;
+                        results
+                            .entry(line.clone())
+                            .or_default()
+                            .insert(wayback.provider().to_string(), result);
+                        num_archived.fetch_add(1, SeqCst);
+                    }
+                    let urls_done = num_urls_done.fetch_add(1, SeqCst) + 1;
+                    overall_bar.set_prefix(format!(
+                        "[{}/{}]",
+                        urls_done,
+                        total_lines_count.load(SeqCst)
+                    ));
+                    continue;
                 }
-                Err(err) => {
-                    if err == ArchiveError::BandwidthExceeded {
-                        pb.set_message("Bandwidth exceeded. Waiting...");
-                        std::thread::sleep(Duration::seconds(15).to_std().expect("sleep duration"));
-                        continue;
+
+                for archiver in &archivers {
+                    let provider = archiver.provider();
+
+                    {
+                        let results = urls.lock().await;
+                        if let Some(existing) =
+                            results.get(&line).and_then(|by_provider| by_provider.get(provider))
+                        {
+                            // If the snapshot is still within the freshness window, accept it and move on.
+                            if (Utc::now().naive_utc() - existing.last_archived) < max_age {
+                                pb.set_message(format!("already archived ({}): {}", provider, line));
+                                continue;
+                            }
+                        }
                     }
-                    pb.finish_with_message(format!("Archiving failed: {} ({})", err, line));
-                    ArchivingResult {
-                        last_archived: Utc::now().naive_local(),
-                        url: None,
-                        existing_snapshot: false,
+
+                    pb.set_message(format!("Archiving {} ({}) ...", line, provider));
+                    let mut timeout_retries = 0;
+                    let result = loop {
+                        match archiver.archive(&line).await {
+                            Ok(success) => {
+                                match &success.url {
+                                    Some(url) => pb.set_message(format!("Done: {}", url)),
+                                    None => pb.set_message(format!(
+                                        "Archived, but no snapshot url returned: {}",
+                                        line
+                                    )),
+                                }
+                                if !success.existing_snapshot {
+                                    pb.set_message(format!(
+                                        "Cooldown after archiving {} ({})...",
+                                        line, provider
+                                    ));
+                                    tokio::time::sleep(
+                                        Duration::seconds(3).to_std().expect("sleep duration"),
+                                    )
+                                    .await;
+                                }
+                                break success;
+                            }
+                            Err(err) => {
+                                if err == ArchiveError::BandwidthExceeded {
+                                    pb.set_message("Bandwidth exceeded. Waiting...");
+                                    tokio::time::sleep(
+                                        Duration::seconds(15).to_std().expect("sleep duration"),
+                                    )
+                                    .await;
+                                    continue;
+                                }
+                                if err == ArchiveError::Timeout
+                                    && timeout_retries < MAX_TIMEOUT_RETRIES
+                                {
+                                    timeout_retries += 1;
+                                    pb.set_message(format!(
+                                        "Timed out, retrying ({}/{}): {}",
+                                        timeout_retries, MAX_TIMEOUT_RETRIES, line
+                                    ));
+                                    continue;
+                                }
+                                pb.set_message(format!("Archiving failed: {} ({})", err, line));
+                                break ArchivingResult {
+                                    last_archived: Utc::now().naive_utc(),
+                                    url: None,
+                                    existing_snapshot: false,
+                                    provider: provider.to_string(),
+                                };
+                            }
+                        }
+                    };
+
+                    let archived_count = {
+                        let mut results = urls.lock().await;
+                        results
+                            .entry(line.clone())
+                            .or_default()
+                            .insert(provider.to_string(), result);
+                        num_archived.fetch_add(1, SeqCst) + 1
+                    };
+
+                    if archived_count % 25 == 0 {
+                        if let Some(out_path) = &out_path {
+                            let results = urls.lock().await;
+                            eprintln!("Writing intermediate results...");
+                            write_results(&results, out_path).expect("write intermediate results");
+                        }
                     }
                 }
-            };
-            urls.insert(line.to_string(), result);
-            break;
-        }
 
-        if (num_archived + 1) % 25 == 0 {
-            if let Some(out_path) = &opts.out {
-                eprintln!("Writing intermediate results...");
-                write_results(&urls, out_path)?;
+                let urls_done = num_urls_done.fetch_add(1, SeqCst) + 1;
+                overall_bar.set_prefix(format!(
+                    "[{}/{}]",
+                    urls_done,
+                    total_lines_count.load(SeqCst)
+                ));
             }
-        }
+
+            pb.finish_and_clear();
+        }));
+    }
+
+    for worker in workers {
+        worker.await?;
     }
+    overall_bar.finish_and_clear();
 
+    let results = urls.lock().await;
     match opts.out {
-        Some(path) => write_results(&urls, &path)?,
+        Some(path) => write_results(&results, &path)?,
         None => {
-            println!("{}", serde_json::to_string_pretty(&urls)?);
+            println!("{}", serde_json::to_string_pretty(&*results)?);
         }
     }
     Ok(())
 }
 
-fn write_results(
-    results: &BTreeMap<String, ArchivingResult>,
-    path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+fn write_results(results: &AllResults, path: &str) -> Result<(), Box<dyn std::error::Error>> {
     let formatted_urls = serde_json::to_string_pretty(&results)?;
     let mut file = fs::OpenOptions::new()
         .write(true)
@@ -174,3 +416,31 @@ fn write_results(
     file.write_all(formatted_urls.as_bytes())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_days_and_weeks() {
+        assert_eq!(parse_duration_spec("180d").unwrap(), Duration::days(180));
+        assert_eq!(parse_duration_spec("12w").unwrap(), Duration::weeks(12));
+        assert_eq!(parse_duration_spec("0d").unwrap(), Duration::days(0));
+    }
+
+    #[test]
+    fn rejects_too_short_specs() {
+        assert!(parse_duration_spec("d").is_err());
+        assert!(parse_duration_spec("").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_units() {
+        assert!(parse_duration_spec("180x").is_err());
+    }
+
+    #[test]
+    fn rejects_non_numeric_amounts() {
+        assert!(parse_duration_spec("abcd").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{AllResults, ArchivingResult};
+use chrono::{Datelike, NaiveDateTime};
+use std::collections::{BTreeMap, HashSet};
+
+/// Retention rules for pruning a results file, in the style of Proxmox's
+/// prune jobs: each rule keeps the newest entry seen in every
+/// still-unfilled bucket of its kind, and an entry survives if any active
+/// rule selects it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RetentionPolicy {
+    pub keep_last: Option<usize>,
+    pub keep_daily: Option<usize>,
+    pub keep_weekly: Option<usize>,
+    pub keep_monthly: Option<usize>,
+    pub keep_yearly: Option<usize>,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.keep_last.is_none()
+            && self.keep_daily.is_none()
+            && self.keep_weekly.is_none()
+            && self.keep_monthly.is_none()
+            && self.keep_yearly.is_none()
+    }
+}
+
+/// Applies `policy` to `results`, returning the pruned map along with the
+/// number of (url, provider) entries that were removed.
+pub fn prune(results: &AllResults, policy: &RetentionPolicy) -> (AllResults, usize) {
+    let mut entries: Vec<(String, String, ArchivingResult)> = results
+        .iter()
+        .flat_map(|(url, by_provider)| {
+            by_provider
+                .iter()
+                .map(move |(provider, result)| (url.clone(), provider.clone(), result.clone()))
+        })
+        .collect();
+    // Newest first, so each rule below fills its buckets with the freshest
+    // entry it encounters for that bucket.
+    entries.sort_by_key(|(_, _, result)| std::cmp::Reverse(result.last_archived));
+
+    let mut keep: HashSet<(String, String)> = HashSet::new();
+
+    if let Some(n) = policy.keep_last {
+        for (url, provider, _) in entries.iter().take(n) {
+            keep.insert((url.clone(), provider.clone()));
+        }
+    }
+
+    type BucketFn = fn(&NaiveDateTime) -> String;
+    let bucket_rules: [(Option<usize>, BucketFn); 4] = [
+        (policy.keep_daily, day_bucket),
+        (policy.keep_weekly, week_bucket),
+        (policy.keep_monthly, month_bucket),
+        (policy.keep_yearly, year_bucket),
+    ];
+
+    for (limit, bucket_fn) in bucket_rules {
+        let limit = match limit {
+            Some(limit) => limit,
+            None => continue,
+        };
+        let mut seen_buckets: BTreeMap<String, ()> = BTreeMap::new();
+        for (url, provider, result) in &entries {
+            if seen_buckets.len() >= limit {
+                break;
+            }
+            let bucket = bucket_fn(&result.last_archived);
+            if seen_buckets.contains_key(&bucket) {
+                continue;
+            }
+            seen_buckets.insert(bucket, ());
+            keep.insert((url.clone(), provider.clone()));
+        }
+    }
+
+    let total = entries.len();
+    let mut pruned: AllResults = BTreeMap::new();
+    for (url, provider, result) in entries {
+        if keep.contains(&(url.clone(), provider.clone())) {
+            pruned.entry(url).or_default().insert(provider, result);
+        }
+    }
+    let removed = total - keep.len();
+    (pruned, removed)
+}
+
+fn day_bucket(ts: &NaiveDateTime) -> String {
+    ts.format("%Y-%m-%d").to_string()
+}
+
+fn week_bucket(ts: &NaiveDateTime) -> String {
+    let iso = ts.iso_week();
+    format!("{}-W{:02}", iso.year(), iso.week())
+}
+
+fn month_bucket(ts: &NaiveDateTime) -> String {
+    ts.format("%Y-%m").to_string()
+}
+
+fn year_bucket(ts: &NaiveDateTime) -> String {
+    ts.format("%Y").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(y: i32, m: u32, d: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    fn result(last_archived: NaiveDateTime) -> ArchivingResult {
+        ArchivingResult {
+            last_archived,
+            url: Some("https://web.archive.org/x".to_string()),
+            existing_snapshot: true,
+            provider: "wayback".to_string(),
+        }
+    }
+
+    fn results(entries: &[(&str, NaiveDateTime)]) -> AllResults {
+        let mut results: AllResults = BTreeMap::new();
+        for (url, last_archived) in entries {
+            results
+                .entry(url.to_string())
+                .or_default()
+                .insert("wayback".to_string(), result(*last_archived));
+        }
+        results
+    }
+
+    #[test]
+    fn keep_last_keeps_the_n_newest_regardless_of_bucket() {
+        let results = results(&[
+            ("a", at(2024, 1, 1)),
+            ("b", at(2024, 1, 2)),
+            ("c", at(2024, 1, 3)),
+        ]);
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            ..Default::default()
+        };
+        let (pruned, removed) = prune(&results, &policy);
+        assert_eq!(removed, 1);
+        assert!(pruned.contains_key("c"));
+        assert!(pruned.contains_key("b"));
+        assert!(!pruned.contains_key("a"));
+    }
+
+    #[test]
+    fn keep_daily_keeps_the_newest_entry_per_day() {
+        let results = results(&[
+            ("morning", at(2024, 1, 1)),
+            ("evening", NaiveDate::from_ymd_opt(2024, 1, 1)
+                .unwrap()
+                .and_hms_opt(18, 0, 0)
+                .unwrap()),
+            ("yesterday", at(2023, 12, 31)),
+        ]);
+        let policy = RetentionPolicy {
+            keep_daily: Some(1),
+            ..Default::default()
+        };
+        let (pruned, removed) = prune(&results, &policy);
+        // Only one bucket (2024-01-01) is requested, and the newer of the
+        // two same-day entries should win the tie.
+        assert_eq!(removed, 2);
+        assert!(pruned.contains_key("evening"));
+        assert!(!pruned.contains_key("morning"));
+        assert!(!pruned.contains_key("yesterday"));
+    }
+
+    #[test]
+    fn entry_survives_if_any_rule_selects_it() {
+        let results = results(&[("a", at(2024, 1, 1)), ("b", at(2023, 1, 1))]);
+        let policy = RetentionPolicy {
+            keep_last: Some(1),
+            keep_yearly: Some(2),
+            ..Default::default()
+        };
+        let (pruned, removed) = prune(&results, &policy);
+        assert_eq!(removed, 0);
+        assert!(pruned.contains_key("a"));
+        assert!(pruned.contains_key("b"));
+    }
+
+    #[test]
+    fn empty_policy_removes_everything() {
+        let results = results(&[("a", at(2024, 1, 1))]);
+        let (pruned, removed) = prune(&results, &RetentionPolicy::default());
+        assert_eq!(removed, 1);
+        assert!(pruned.is_empty());
+    }
+
+    #[test]
+    fn is_empty_reflects_whether_any_rule_is_set() {
+        assert!(RetentionPolicy::default().is_empty());
+        assert!(!RetentionPolicy {
+            keep_last: Some(1),
+            ..Default::default()
+        }
+        .is_empty());
+    }
+}
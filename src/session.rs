@@ -0,0 +1,137 @@
+use crate::{build_archiver, Archiver, ArchivingResult};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Semaphore};
+
+/// Builds an [`ArchivingSession`]. Request timeout, concurrency and archive
+/// backend(s) correspond to CLI flags; the politeness delay observed after
+/// each archival is configurable here only (the CLI uses a fixed 3 seconds).
+pub struct ArchivingSessionBuilder {
+    timeout: Duration,
+    concurrency: usize,
+    providers: Vec<String>,
+    politeness_delay: Duration,
+}
+
+impl ArchivingSessionBuilder {
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            concurrency: 4,
+            providers: vec!["wayback".to_string()],
+            politeness_delay: Duration::from_secs(3),
+        }
+    }
+
+    /// How long to wait for a single archival request to complete.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many archivals (one URL against one backend) to have in flight
+    /// at once.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Which backend(s) to submit each URL to, e.g. `["wayback"]` or
+    /// `["wayback", "archive-today"]`.
+    pub fn providers(mut self, providers: Vec<String>) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    /// How long an archival keeps its concurrency slot after any result
+    /// that is not an existing snapshot, failed attempts included.
+    pub fn politeness_delay(mut self, delay: Duration) -> Self {
+        self.politeness_delay = delay;
+        self
+    }
+
+    pub fn build(self) -> Result<ArchivingSession, String> {
+        let client = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(|err| err.to_string())?;
+        let archivers = self
+            .providers
+            .iter()
+            .map(|name| build_archiver(name, client.clone()))
+            .collect::<Result<_, _>>()?;
+        Ok(ArchivingSession {
+            archivers,
+            concurrency: self.concurrency,
+            politeness_delay: self.politeness_delay,
+        })
+    }
+}
+
+impl Default for ArchivingSessionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A configured set of archive backends ready to archive URLs. Build one
+/// with [`ArchivingSessionBuilder`].
+pub struct ArchivingSession {
+    archivers: Vec<Arc<dyn Archiver>>,
+    concurrency: usize,
+    politeness_delay: Duration,
+}
+
+impl ArchivingSession {
+    /// Archives every URL in `urls` against every configured backend,
+    /// returning a channel that yields `(url, provider, result)` as each
+    /// archival completes. Results arrive in completion order, not input
+    /// order. At most `concurrency` archivals are in flight at once.
+    ///
+    /// Unlike the CLI, this does not retry timeouts or back off on
+    /// bandwidth limits. A failed archival is yielded as a result with
+    /// `url: None`; the `ArchiveError` that caused it is not returned.
+    pub fn archive_stream<I>(&self, urls: I) -> mpsc::Receiver<(String, String, ArchivingResult)>
+    where
+        I: IntoIterator<Item = String> + Send + 'static,
+        I::IntoIter: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(self.concurrency.max(1) * 2);
+        let archivers = self.archivers.clone();
+        let semaphore = Arc::new(Semaphore::new(self.concurrency.max(1)));
+        let politeness_delay = self.politeness_delay;
+
+        tokio::spawn(async move {
+            let mut tasks = Vec::new();
+            for url in urls {
+                for archiver in archivers.iter() {
+                    let tx = tx.clone();
+                    let semaphore = semaphore.clone();
+                    let url = url.clone();
+                    let archiver = archiver.clone();
+                    tasks.push(tokio::spawn(async move {
+                        let _permit = semaphore.acquire().await.expect("semaphore closed");
+                        let provider = archiver.provider().to_string();
+                        let result = archiver.archive(&url).await.unwrap_or_else(|_| {
+                            ArchivingResult {
+                                last_archived: chrono::Utc::now().naive_utc(),
+                                url: None,
+                                existing_snapshot: false,
+                                provider: provider.clone(),
+                            }
+                        });
+                        if !result.existing_snapshot {
+                            tokio::time::sleep(politeness_delay).await;
+                        }
+                        let _ = tx.send((url, provider, result)).await;
+                    }));
+                }
+            }
+            for task in tasks {
+                let _ = task.await;
+            }
+        });
+
+        rx
+    }
+}